@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::types::{Answer, Label, Question, RecordClass, RecordType};
+
+/// A cached answer together with the absolute instant at which it expires,
+/// computed from the record's TTL when it was learned.
+struct Entry {
+    answer: Answer,
+    expiry: Instant,
+}
+
+/// Keyed by `(name, record_type, record_class)`. Names are lower-cased so the
+/// case-insensitive matching DNS mandates doesn't fragment the cache.
+type Key = (String, u16, u16);
+
+/// An in-memory answer cache following the learn/lookup/housekeep pattern:
+/// answers learned from upstream are served back until their TTL elapses, and
+/// a periodic housekeep pass reclaims expired entries.
+#[derive(Default)]
+pub struct Cache {
+    entries: HashMap<Key, Entry>,
+}
+
+fn name_key(name: &[Label]) -> String {
+    name.iter()
+        .map(|l| l.0.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn key(name: &[Label], record_type: RecordType, record_class: RecordClass) -> Key {
+    (name_key(name), record_type.into(), record_class.into())
+}
+
+impl Cache {
+    pub fn new() -> Self {
+        Cache::default()
+    }
+
+    /// Return a cached answer for `question` if one is present and unexpired,
+    /// with its TTL rewritten to the number of seconds left before expiry.
+    pub fn lookup(&self, question: &Question) -> Option<Answer> {
+        let k = key(&question.name, question.record_type, question.record_class);
+        let entry = self.entries.get(&k)?;
+        let remaining = entry.expiry.checked_duration_since(Instant::now())?;
+        let mut answer = entry.answer.clone();
+        answer.ttl = remaining.as_secs() as u32;
+        Some(answer)
+    }
+
+    /// Remember `answer` until its TTL elapses.
+    pub fn learn(&mut self, answer: &Answer) {
+        let k = key(&answer.name, answer.record_type, answer.record_class);
+        let entry = Entry {
+            answer: answer.clone(),
+            expiry: Instant::now() + Duration::from_secs(answer.ttl as u64),
+        };
+        self.entries.insert(k, entry);
+    }
+
+    /// Evict every entry whose TTL has elapsed.
+    pub fn housekeep(&mut self) {
+        let now = Instant::now();
+        self.entries.retain(|_, entry| entry.expiry > now);
+    }
+}
@@ -1,17 +1,32 @@
-use std::net::UdpSocket;
-use types::{Answer, Header, Message, OpCode, RCode};
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use cache::Cache;
+use types::{
+    ARData, Answer, Header, Label, Message, NsRData, OpCode, Opt, Question, RCode, RecordClass,
+    RecordType, TxtRData,
+};
+
+mod cache;
 mod parser;
 mod types;
 
+// a.root-servers.net — the entry point for iterative resolution.
+const ROOT_SERVER: Ipv4Addr = Ipv4Addr::new(198, 41, 0, 4);
+// Guard against a delegation chain that never bottoms out at an answer.
+const MAX_DELEGATIONS: usize = 16;
+// The UDP payload size we advertise (and accept) over EDNS0.
+const MAX_UDP_PAYLOAD: u16 = 4096;
+
 fn forward_message(message: Message, socket: &UdpSocket) -> anyhow::Result<Answer> {
     let message_bytes: Vec<u8> = message.try_into().unwrap();
     socket.send(message_bytes.as_slice())?;
 
-    let mut buf = [0; 512];
-    socket.recv(&mut buf)?;
+    let mut buf = [0; MAX_UDP_PAYLOAD as usize];
+    let len = socket.recv(&mut buf)?;
     let mut dns_parser = parser::DnsParser {
-        packet: buf,
+        packet: buf[..len].to_vec(),
         pos: 0,
     };
     let answer_message = dns_parser.parse()?;
@@ -23,9 +38,114 @@ fn forward_message(message: Message, socket: &UdpSocket) -> anyhow::Result<Answe
     }
 }
 
-fn handle_connection(buf: [u8; 512], forwarding_addr: Option<String>) -> anyhow::Result<Message> {
+/// Send `question` to a single name server and parse the reply.
+fn lookup(question: &Question, server: Ipv4Addr) -> anyhow::Result<Message> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    let query = Message {
+        header: Header {
+            id: 0x1234,
+            qdcount: 1,
+            ..Default::default()
+        },
+        questions: vec![question.clone()],
+        ..Default::default()
+    };
+    let query_bytes: Vec<u8> = query.try_into()?;
+    socket.send_to(&query_bytes, (server, 53))?;
+
+    let mut buf = [0; MAX_UDP_PAYLOAD as usize];
+    let (len, _) = socket.recv_from(&mut buf)?;
     let mut dns_parser = parser::DnsParser {
-        packet: buf,
+        packet: buf[..len].to_vec(),
+        pos: 0,
+    };
+    dns_parser.parse()
+}
+
+/// The IPv4 address carried by an A record, if `answer` is one.
+fn a_addr(answer: &Answer) -> Option<Ipv4Addr> {
+    answer
+        .rdata
+        .as_any()
+        .downcast_ref::<ARData>()
+        .map(|data| data.0)
+}
+
+fn names_equal(a: &[Label], b: &[Label]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.0.eq_ignore_ascii_case(&y.0))
+}
+
+/// Find a name server in the authority section that has a matching A glue
+/// record in the additional section, returning its address.
+fn resolved_ns(response: &Message) -> Option<Ipv4Addr> {
+    for ns in &response.authority.0 {
+        if let Some(ns_data) = ns.rdata.as_any().downcast_ref::<NsRData>() {
+            for glue in &response.additional.0 {
+                if names_equal(&glue.name, &ns_data.0) {
+                    if let Some(addr) = a_addr(glue) {
+                        return Some(addr);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// A name server named in the authority section that lacks a glue record and
+/// therefore needs to be resolved on its own.
+fn unresolved_ns(response: &Message) -> Option<Vec<Label>> {
+    response
+        .authority
+        .0
+        .iter()
+        .find_map(|ns| ns.rdata.as_any().downcast_ref::<NsRData>().map(|d| d.0.clone()))
+}
+
+/// Resolve `question` from the root servers down, following delegations until
+/// an answer is reached or the iteration cap is hit.
+fn resolve_recursive(question: &Question) -> anyhow::Result<Answer> {
+    let mut ns = ROOT_SERVER;
+    for _ in 0..MAX_DELEGATIONS {
+        let response = lookup(question, ns)?;
+
+        if let Some(answer) = response.answers.first() {
+            return Ok(answer.clone());
+        }
+
+        // Prefer a delegation we can follow directly via its glue record.
+        if let Some(next) = resolved_ns(&response) {
+            ns = next;
+            continue;
+        }
+
+        // Otherwise resolve the name server's own address first, then retry.
+        if let Some(ns_name) = unresolved_ns(&response) {
+            let ns_question = Question {
+                name: ns_name,
+                record_type: RecordType::A,
+                record_class: RecordClass::IN,
+            };
+            let ns_answer = resolve_recursive(&ns_question)?;
+            if let Some(addr) = a_addr(&ns_answer) {
+                ns = addr;
+                continue;
+            }
+        }
+
+        return Err(anyhow::anyhow!("no answer and no name server to follow"));
+    }
+    Err(anyhow::anyhow!("exceeded max delegations"))
+}
+
+fn handle_connection(
+    buf: &[u8],
+    forwarding_addr: Option<String>,
+    recursive: bool,
+    cache: &Arc<Mutex<Cache>>,
+) -> anyhow::Result<Message> {
+    let mut dns_parser = parser::DnsParser {
+        packet: buf.to_vec(),
         pos: 0,
     };
     let query_message = dns_parser.parse()?;
@@ -45,6 +165,15 @@ fn handle_connection(buf: [u8; 512], forwarding_addr: Option<String>) -> anyhow:
         .header
         .set_rd(query_message.header.get_rd());
 
+    // Echo EDNS0 support back to peers that advertised it, advertising our own
+    // payload size so larger responses aren't clipped at 512 bytes.
+    if query_message.opt.is_some() {
+        response_message.opt = Some(Opt {
+            payload_size: MAX_UDP_PAYLOAD,
+        });
+        response_message.header.arcount += 1;
+    }
+
     if opcode != OpCode::Query {
         response_message.header.set_rcode(RCode::NotImplemented);
         return Ok(response_message);
@@ -52,28 +181,52 @@ fn handle_connection(buf: [u8; 512], forwarding_addr: Option<String>) -> anyhow:
 
     let mut answers: Vec<Answer> = vec![];
 
-    if let Some(addr) = forwarding_addr {
-        let forward_socket = UdpSocket::bind("127.0.0.1:8888").expect("Failed to bind to address");
-        forward_socket.connect(addr.clone()).unwrap_or_else(|_| {
-            panic!("couldn't connect to forwarding server on {}", addr.clone())
-        });
-        for i in 0..query_message.header.qdcount {
-            let mut forwarding_message = query_message.clone();
-            forwarding_message.questions = vec![questions[i as usize].clone()];
-            let answer = forward_message(forwarding_message, &forward_socket)?;
+    let forward_socket = forwarding_addr.map(|addr| {
+        let socket = UdpSocket::bind("127.0.0.1:8888").expect("Failed to bind to address");
+        socket
+            .connect(addr.clone())
+            .unwrap_or_else(|_| panic!("couldn't connect to forwarding server on {}", addr));
+        socket
+    });
+
+    for question in questions.iter() {
+        // Serve from cache before reaching out to any upstream.
+        if let Some(answer) = cache.lock().unwrap().lookup(question) {
             answers.push(answer);
+            continue;
         }
-    } else {
-        for question in questions.iter() {
-            let a = Answer {
+
+        let answer = if let Some(socket) = &forward_socket {
+            let mut forwarding_message = query_message.clone();
+            forwarding_message.questions = vec![question.clone()];
+            forward_message(forwarding_message, socket)?
+        } else if recursive {
+            resolve_recursive(question)?
+        } else {
+            let rdata: Box<dyn types::RData> = match question.record_type {
+                RecordType::Txt => Box::new(TxtRData::from_payload(b"crate dns-server")),
+                _ => Box::new(ARData(Ipv4Addr::new(8, 8, 8, 8))),
+            };
+            Answer {
                 name: question.name.clone(),
                 record_type: question.record_type,
                 record_class: question.record_class,
                 ttl: 60,
-                rdata: vec![8, 8, 8, 8],
-            };
-            answers.push(a);
+                rdata,
+            }
+        };
+
+        // TXT answers can tunnel an arbitrary payload; surface the reassembled
+        // bytes for visibility.
+        if let Some(txt) = answer.rdata.as_any().downcast_ref::<TxtRData>() {
+            eprintln!(
+                "txt record payload: {}",
+                String::from_utf8_lossy(&txt.payload())
+            );
         }
+
+        cache.lock().unwrap().learn(&answer);
+        answers.push(answer);
     }
 
     response_message.header.ancount = answers.len() as u16;
@@ -85,19 +238,36 @@ fn handle_connection(buf: [u8; 512], forwarding_addr: Option<String>) -> anyhow:
 
 fn main() {
     let mut forwarding_addr: Option<String> = None;
+    let mut recursive = false;
     let args: Vec<_> = std::env::args().map(|a| a.to_string()).collect();
     for i in 0..args.len() {
         if args[i] == "--resolver" {
             forwarding_addr = Some(args[i + 1].clone());
-            break;
+        } else if args[i] == "--recursive" {
+            recursive = true;
         }
     }
 
+    let cache = Arc::new(Mutex::new(Cache::new()));
+    {
+        // Periodically reclaim entries whose TTL has elapsed.
+        let cache = Arc::clone(&cache);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(60));
+            cache.lock().unwrap().housekeep();
+        });
+    }
+
     let udp_socket = UdpSocket::bind("127.0.0.1:2053").expect("Failed to bind to address");
-    let mut buf = [0; 512];
+    let mut buf = [0; MAX_UDP_PAYLOAD as usize];
     loop {
         match udp_socket.recv_from(&mut buf) {
-            Ok((_, source)) => match handle_connection(buf, forwarding_addr.clone()) {
+            Ok((size, source)) => match handle_connection(
+                &buf[..size],
+                forwarding_addr.clone(),
+                recursive,
+                &cache,
+            ) {
                 Ok(message) => {
                     let response: Vec<u8> = message.try_into().unwrap();
                     udp_socket
@@ -1,4 +1,21 @@
-use crate::types::{Answer, Header, Label, Message, Question, RecordType};
+use std::net::Ipv4Addr;
+
+use crate::types::{
+    ARData, Additional, Answer, Authority, CnameRData, Header, Label, Message, MxRData, NsRData,
+    Opt, OptRData, Question, RData, RecordType, TxtRData,
+};
+
+/// An additional-section record: either an ordinary resource record or the
+/// EDNS0 OPT pseudo-record.
+enum AdditionalRecord {
+    Record(Answer),
+    Opt(Opt),
+}
+
+// A single name may legitimately follow a compression pointer to a previous
+// name, but never a long chain of them. Cap the jumps a crafted packet can
+// make us perform so a self-referential pointer can't spin the parse forever.
+const MAX_JUMPS: usize = 5;
 
 pub struct DnsParser {
     pub packet: Vec<u8>,
@@ -6,38 +23,65 @@ pub struct DnsParser {
 }
 
 impl DnsParser {
+    /// Borrow `len` bytes starting at `start`, erroring instead of panicking
+    /// when the packet is truncated.
+    fn take(&self, start: usize, len: usize) -> anyhow::Result<&[u8]> {
+        self.packet
+            .get(start..start + len)
+            .ok_or_else(|| anyhow::anyhow!("unexpected end of packet"))
+    }
+
     fn parse_header(&mut self) -> anyhow::Result<Header> {
-        let header_bytes: [u8; 12] = self.packet[0..12].try_into()?;
+        let header_bytes: [u8; 12] = self.take(0, 12)?.try_into()?;
         self.pos = 12;
         Header::try_from(header_bytes)
     }
 
     fn parse_labels(&mut self) -> anyhow::Result<Vec<Label>> {
+        self.parse_labels_inner(0)
+    }
+
+    fn parse_labels_inner(&mut self, jumps: usize) -> anyhow::Result<Vec<Label>> {
         let mut labels: Vec<Label> = vec![];
         while let Some(b) = self.packet.get(self.pos) {
+            let b = *b;
             self.pos += 1;
             // null terminator
-            if *b == 0 {
+            if b == 0 {
                 break;
             }
 
             // jump instruction
-            if (*b & 0b1100_0000) == 0b1100_0000 {
+            if (b & 0b1100_0000) == 0b1100_0000 {
                 // if the two Most Significant Bits of the length is set, we can instead expect the length byte to be followed by a second byte.
                 // These two bytes taken together, and removing the two MSB's, indicate the jump position
+                if jumps >= MAX_JUMPS {
+                    return Err(anyhow::anyhow!(
+                        "too many compression pointers while parsing name"
+                    ));
+                }
                 // get the jump position
-                let jump_pos = u16::from_be_bytes([*b & 0b0011_1111, self.packet[self.pos]]);
+                let second = *self
+                    .packet
+                    .get(self.pos)
+                    .ok_or_else(|| anyhow::anyhow!("truncated compression pointer"))?;
+                let jump_pos = u16::from_be_bytes([b & 0b0011_1111, second]) as usize;
                 self.pos += 1;
+                // Only ever jump strictly backwards and within the buffer; a
+                // forward or self-referential target could loop indefinitely.
+                if jump_pos >= self.pos - 2 || jump_pos >= self.packet.len() {
+                    return Err(anyhow::anyhow!("invalid compression pointer: {jump_pos}"));
+                }
                 let current_pos = self.pos;
-                self.pos = jump_pos as usize;
-                labels.extend(self.parse_labels()?);
+                self.pos = jump_pos;
+                labels.extend(self.parse_labels_inner(jumps + 1)?);
                 self.pos = current_pos;
                 return Ok(labels);
             }
 
-            let length = *b as usize;
+            let length = b as usize;
             // skip the length byte
-            let s = String::from_utf8(self.packet[self.pos..self.pos + length].to_vec())?;
+            let s = String::from_utf8(self.take(self.pos, length)?.to_vec())?;
             labels.push(Label(s));
             self.pos += length;
         }
@@ -47,9 +91,8 @@ impl DnsParser {
     fn parse_question(&mut self) -> anyhow::Result<Question> {
         let labels = self.parse_labels()?;
 
-        let record_type = RecordType::try_from(u16::from_be_bytes(
-            self.packet[self.pos..=self.pos + 1].try_into()?,
-        ))?;
+        let record_type =
+            RecordType::try_from(u16::from_be_bytes(self.take(self.pos, 2)?.try_into()?))?;
         // skip record type bytes
         self.pos += 2;
 
@@ -67,23 +110,25 @@ impl DnsParser {
     fn parse_answer(&mut self) -> anyhow::Result<Answer> {
         let labels = self.parse_labels()?;
 
-        let record_type = RecordType::try_from(u16::from_be_bytes(
-            self.packet[self.pos..=self.pos + 1].try_into()?,
-        ))?;
+        let record_type =
+            RecordType::try_from(u16::from_be_bytes(self.take(self.pos, 2)?.try_into()?))?;
         // skip record type bytes
         self.pos += 2;
 
         // skip record class bytes
         self.pos += 2;
 
-        let ttl = u32::from_be_bytes(self.packet[self.pos..self.pos + 4].try_into()?);
+        let ttl = u32::from_be_bytes(self.take(self.pos, 4)?.try_into()?);
         self.pos += 4;
 
-        let rdlength = u16::from_be_bytes(self.packet[self.pos..self.pos + 2].try_into()?);
+        let rdlength = u16::from_be_bytes(self.take(self.pos, 2)?.try_into()?);
         self.pos += 2;
 
-        let rdata = self.packet[self.pos..self.pos + rdlength as usize].to_vec();
-        self.pos += rdlength as usize;
+        let rdata_start = self.pos;
+        let rdata = self.parse_rdata(record_type, rdlength as usize)?;
+        // RDATA may contain compression pointers that leave `pos` somewhere
+        // earlier in the packet; always resume right after the RDATA region.
+        self.pos = rdata_start + rdlength as usize;
 
         let q = Answer {
             name: labels,
@@ -95,6 +140,79 @@ impl DnsParser {
         Ok(q)
     }
 
+    /// Decode the RDATA region for `record_type`, dispatching on the record
+    /// type. Label targets (NS/CNAME/MX) honour compression pointers into the
+    /// rest of the packet.
+    fn parse_rdata(
+        &mut self,
+        record_type: RecordType,
+        rdlength: usize,
+    ) -> anyhow::Result<Box<dyn RData>> {
+        match record_type {
+            RecordType::A => {
+                let octets: [u8; 4] = self.take(self.pos, 4)?.try_into()?;
+                Ok(Box::new(ARData(Ipv4Addr::from(octets))))
+            }
+            RecordType::NS => Ok(Box::new(NsRData(self.parse_labels()?))),
+            RecordType::Cname => Ok(Box::new(CnameRData(self.parse_labels()?))),
+            RecordType::MX => {
+                let preference = u16::from_be_bytes(self.take(self.pos, 2)?.try_into()?);
+                self.pos += 2;
+                let exchange = self.parse_labels()?;
+                Ok(Box::new(MxRData {
+                    preference,
+                    exchange,
+                }))
+            }
+            RecordType::Txt => {
+                let bytes = self.take(self.pos, rdlength)?.to_vec();
+                Ok(Box::new(TxtRData::from_bytes(&bytes)?))
+            }
+            // OPT records are consumed via `parse_additional`, which reads the
+            // payload size out of the CLASS field; the options are not retained.
+            RecordType::Opt => Ok(Box::new(OptRData(Vec::new()))),
+        }
+    }
+
+    /// Parse one additional-section record, recognising the EDNS0 OPT
+    /// pseudo-record (type 41) whose CLASS field carries the UDP payload size.
+    fn parse_additional(&mut self) -> anyhow::Result<AdditionalRecord> {
+        let name = self.parse_labels()?;
+        let type_raw = u16::from_be_bytes(self.take(self.pos, 2)?.try_into()?);
+        self.pos += 2;
+
+        if type_raw == u16::from(RecordType::Opt) {
+            let payload_size = u16::from_be_bytes(self.take(self.pos, 2)?.try_into()?);
+            self.pos += 2; // CLASS = payload size
+            self.pos += 4; // TTL = extended rcode/version/flags
+            let rdlength = u16::from_be_bytes(self.take(self.pos, 2)?.try_into()?);
+            self.pos += 2;
+            self.pos += rdlength as usize; // options (unused)
+            return Ok(AdditionalRecord::Opt(Opt { payload_size }));
+        }
+
+        let record_type = RecordType::try_from(type_raw)?;
+        self.pos += 2; // CLASS
+
+        let ttl = u32::from_be_bytes(self.take(self.pos, 4)?.try_into()?);
+        self.pos += 4;
+
+        let rdlength = u16::from_be_bytes(self.take(self.pos, 2)?.try_into()?);
+        self.pos += 2;
+
+        let rdata_start = self.pos;
+        let rdata = self.parse_rdata(record_type, rdlength as usize)?;
+        self.pos = rdata_start + rdlength as usize;
+
+        Ok(AdditionalRecord::Record(Answer {
+            name,
+            record_type,
+            ttl,
+            rdata,
+            ..Default::default()
+        }))
+    }
+
     pub fn parse(&mut self) -> anyhow::Result<Message> {
         let header = self.parse_header()?;
 
@@ -106,11 +224,26 @@ impl DnsParser {
             (0..header.ancount).map(|_| self.parse_answer()).collect();
         let answers = answers?;
 
+        // Authority records share the resource-record format with answers.
+        let authority: Result<Vec<Answer>, _> =
+            (0..header.nscount).map(|_| self.parse_answer()).collect();
+
+        let mut additional = vec![];
+        let mut opt = None;
+        for _ in 0..header.arcount {
+            match self.parse_additional()? {
+                AdditionalRecord::Record(answer) => additional.push(answer),
+                AdditionalRecord::Opt(o) => opt = Some(o),
+            }
+        }
+
         Ok(Message {
             header,
             questions,
             answers,
-            ..Default::default()
+            authority: Authority(authority?),
+            additional: Additional(additional),
+            opt,
         })
     }
 }
@@ -129,7 +262,28 @@ fn test_parser_decompress() {
         pos: 0,
     };
 
-    parser.parse().unwrap();
+    let message = parser.parse().unwrap();
+
+    assert_eq!(message.questions.len(), 2);
+    let def = &message.questions[1].name;
+    // "def" followed by a pointer back to "longassdomainname.com"
+    let labels: Vec<_> = def.iter().map(|l| l.0.as_str()).collect();
+    assert_eq!(labels, ["def", "longassdomainname", "com"]);
+}
+
+#[test]
+fn test_parser_rejects_pointer_loop() {
+    // A question whose name is a compression pointer to itself: without a
+    // jump cap and a backwards-only check this would recurse forever.
+    let message_bytes: &[u8] = &[
+        0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, // header, one question
+        0b1100_0000, 12, 0, 1, 0, 1, // name = pointer to offset 12 (itself)
+    ];
+
+    let mut parser = DnsParser {
+        packet: message_bytes.to_vec(),
+        pos: 0,
+    };
 
-    assert!(true);
+    assert!(parser.parse().is_err());
 }
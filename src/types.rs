@@ -1,5 +1,32 @@
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
 use bytes::{BufMut, BytesMut};
 
+/// Write a domain name into `buf`, compressing it against names already
+/// emitted. `offsets` maps a name suffix to the offset it was first written
+/// at; the longest matching suffix is replaced with a two-byte pointer and
+/// only the labels preceding it are written literally.
+fn write_name(buf: &mut BytesMut, name: &[Label], offsets: &mut HashMap<Vec<Label>, u16>) {
+    let mut remaining = name;
+    while !remaining.is_empty() {
+        if let Some(&offset) = offsets.get(remaining) {
+            buf.put_u16(0b1100_0000_0000_0000 | offset);
+            return;
+        }
+        let pos = buf.len() as u16;
+        // Pointers only address the low 14 bits; don't record names written
+        // past that boundary as compression targets.
+        if pos < 0x4000 {
+            offsets.insert(remaining.to_vec(), pos);
+        }
+        let label_bytes: Vec<u8> = remaining[0].clone().into();
+        buf.extend_from_slice(&label_bytes);
+        remaining = &remaining[1..];
+    }
+    buf.put_u8(0);
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum OpCode {
     Query,
@@ -40,6 +67,8 @@ pub enum RCode {
     NameError,
     NotImplemented,
     Refused,
+    /// Extended rcode (EDNS0): the requestor's OPT version is unsupported.
+    BadVers,
 }
 
 impl From<RCode> for u8 {
@@ -51,6 +80,7 @@ impl From<RCode> for u8 {
             RCode::NameError => 3,
             RCode::NotImplemented => 4,
             RCode::Refused => 5,
+            RCode::BadVers => 16,
         }
     }
 }
@@ -146,6 +176,10 @@ pub enum RecordType {
     NS,
     Cname,
     MX,
+    /// TXT record: one or more free-form character-strings.
+    Txt,
+    /// EDNS0 pseudo-record carrying the UDP payload size and extended flags.
+    Opt,
 }
 
 impl TryFrom<u16> for RecordType {
@@ -157,6 +191,8 @@ impl TryFrom<u16> for RecordType {
             2 => Ok(RecordType::NS),
             5 => Ok(RecordType::Cname),
             15 => Ok(RecordType::MX),
+            16 => Ok(RecordType::Txt),
+            41 => Ok(RecordType::Opt),
             _ => Err(anyhow::anyhow!("Unknown record type: {}", value)),
         }
     }
@@ -169,11 +205,13 @@ impl From<RecordType> for u16 {
             RecordType::NS => 2,
             RecordType::Cname => 5,
             RecordType::MX => 15,
+            RecordType::Txt => 16,
+            RecordType::Opt => 41,
         }
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Label(pub String);
 
 impl From<Label> for Vec<u8> {
@@ -199,6 +237,176 @@ impl From<RecordClass> for u16 {
     }
 }
 
+/// Encode a sequence of labels in full (uncompressed) wire form: each label as
+/// a length byte followed by its bytes, terminated by a zero-length label.
+fn encode_labels(labels: &[Label]) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    for label in labels {
+        let label_bytes: Vec<u8> = label.clone().into();
+        buf.extend_from_slice(&label_bytes);
+    }
+    buf.put_u8(0);
+    buf.into()
+}
+
+/// The record-type-specific payload of an `Answer`. `to_bytes` renders the
+/// RDATA region (everything after the two-byte RDLENGTH); decoding lives in
+/// [`crate::parser::DnsParser`] since label targets may follow compression
+/// pointers into earlier parts of the packet.
+pub trait RData: std::fmt::Debug + Send + Sync {
+    fn to_bytes(&self) -> Vec<u8>;
+    fn clone_box(&self) -> Box<dyn RData>;
+    /// Escape hatch so callers (e.g. the recursive resolver) can downcast back
+    /// to a concrete record and read its fields.
+    fn as_any(&self) -> &dyn std::any::Any;
+}
+
+impl Clone for Box<dyn RData> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// A record: the four address octets.
+#[derive(Clone, Debug)]
+pub struct ARData(pub Ipv4Addr);
+
+/// NS record: the authoritative name server's domain name.
+#[derive(Clone, Debug)]
+pub struct NsRData(pub Vec<Label>);
+
+/// CNAME record: the canonical name the queried name aliases to.
+#[derive(Clone, Debug)]
+pub struct CnameRData(pub Vec<Label>);
+
+/// MX record: a mail exchange and its preference.
+#[derive(Clone, Debug)]
+pub struct MxRData {
+    pub preference: u16,
+    pub exchange: Vec<Label>,
+}
+
+impl RData for ARData {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.octets().to_vec()
+    }
+    fn clone_box(&self) -> Box<dyn RData> {
+        Box::new(self.clone())
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+impl RData for NsRData {
+    fn to_bytes(&self) -> Vec<u8> {
+        encode_labels(&self.0)
+    }
+    fn clone_box(&self) -> Box<dyn RData> {
+        Box::new(self.clone())
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+impl RData for CnameRData {
+    fn to_bytes(&self) -> Vec<u8> {
+        encode_labels(&self.0)
+    }
+    fn clone_box(&self) -> Box<dyn RData> {
+        Box::new(self.clone())
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+impl RData for MxRData {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        buf.put_u16(self.preference);
+        buf.extend_from_slice(&encode_labels(&self.exchange));
+        buf.into()
+    }
+    fn clone_box(&self) -> Box<dyn RData> {
+        Box::new(self.clone())
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// TXT record: one or more length-prefixed character-strings. Each string is
+/// at most 255 bytes; together they can carry an arbitrary payload tunneled
+/// inside a structurally valid DNS message.
+#[derive(Clone, Debug, Default)]
+pub struct TxtRData(pub Vec<Vec<u8>>);
+
+impl TxtRData {
+    /// Split an arbitrary payload into the ≤255-byte character-strings a TXT
+    /// record is made of.
+    pub fn from_payload(payload: &[u8]) -> Self {
+        TxtRData(payload.chunks(255).map(<[u8]>::to_vec).collect())
+    }
+
+    /// Reassemble the character-strings back into the original payload.
+    pub fn payload(&self) -> Vec<u8> {
+        self.0.concat()
+    }
+
+    /// Decode a TXT RDATA region: successive length-prefixed character-strings
+    /// filling `bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut strings = vec![];
+        let mut i = 0;
+        while i < bytes.len() {
+            let length = bytes[i] as usize;
+            i += 1;
+            let end = i + length;
+            if end > bytes.len() {
+                return Err(anyhow::anyhow!("truncated TXT character-string"));
+            }
+            strings.push(bytes[i..end].to_vec());
+            i = end;
+        }
+        Ok(TxtRData(strings))
+    }
+}
+
+impl RData for TxtRData {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        for s in &self.0 {
+            buf.put_u8(s.len() as u8);
+            buf.extend_from_slice(s);
+        }
+        buf.into()
+    }
+    fn clone_box(&self) -> Box<dyn RData> {
+        Box::new(self.clone())
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// OPT pseudo-record RDATA: a concatenation of EDNS options, kept opaque.
+#[derive(Clone, Debug, Default)]
+pub struct OptRData(pub Vec<u8>);
+
+impl RData for OptRData {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+    fn clone_box(&self) -> Box<dyn RData> {
+        Box::new(self.clone())
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
 /**
    Each question has the following structure:
        Name: A domain name, represented as a sequence of "labels"
@@ -212,62 +420,70 @@ pub struct Question {
     pub record_class: RecordClass,
 }
 
-impl From<Question> for Vec<u8> {
-    fn from(val: Question) -> Self {
-        let mut buf = BytesMut::new();
-
-        val.name.into_iter().for_each(|l| {
-            let label_bytes: Vec<u8> = l.into();
-            buf.extend_from_slice(label_bytes.as_slice());
-        });
-        buf.put_u8(0);
-
-        buf.put_u16(val.record_type.into());
-
-        buf.put_u16(val.record_class.into());
-
-        buf.into()
+impl Question {
+    /// Serialize this question into `buf`, compressing its name against names
+    /// already written (tracked in `offsets`).
+    fn write(&self, buf: &mut BytesMut, offsets: &mut HashMap<Vec<Label>, u16>) {
+        write_name(buf, &self.name, offsets);
+        buf.put_u16(self.record_type.into());
+        buf.put_u16(self.record_class.into());
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct Answer {
     pub name: Vec<Label>,
     pub record_type: RecordType,
     pub record_class: RecordClass,
     pub ttl: u32,
-    pub rdata: Vec<u8>,
+    pub rdata: Box<dyn RData>,
 }
 
-impl From<Answer> for Vec<u8> {
-    fn from(val: Answer) -> Self {
-        let mut buf = BytesMut::with_capacity(512);
-        val.name.into_iter().for_each(|l| {
-            let label_bytes: Vec<u8> = l.into();
-            buf.extend_from_slice(label_bytes.as_slice());
-        });
-        buf.put_u8(0);
-
-        buf.put_u16(val.record_type.into());
-
-        buf.put_u16(val.record_class.into());
+impl Default for Answer {
+    fn default() -> Self {
+        Answer {
+            name: vec![],
+            record_type: RecordType::default(),
+            record_class: RecordClass::default(),
+            ttl: 0,
+            rdata: Box::new(ARData(Ipv4Addr::UNSPECIFIED)),
+        }
+    }
+}
 
-        buf.put_u32(val.ttl);
+impl Answer {
+    /// Serialize this resource record into `buf`, compressing its owner name
+    /// against names already written (tracked in `offsets`).
+    fn write(&self, buf: &mut BytesMut, offsets: &mut HashMap<Vec<Label>, u16>) {
+        write_name(buf, &self.name, offsets);
+        buf.put_u16(self.record_type.into());
+        buf.put_u16(self.record_class.into());
+        buf.put_u32(self.ttl);
 
+        let rdata = self.rdata.to_bytes();
         // rdlength
-        buf.put_u16(val.rdata.len() as u16);
-
-        buf.extend_from_slice(&val.rdata[..]);
-
-        buf.into()
+        buf.put_u16(rdata.len() as u16);
+        buf.extend_from_slice(&rdata[..]);
     }
 }
 
+/// The authority section: NS records pointing at the name servers for the
+/// queried zone. Shares the resource-record wire format with answers.
 #[derive(Clone, Debug, Default)]
-pub struct Authority {}
+pub struct Authority(pub Vec<Answer>);
 
+/// The additional section: typically the A "glue" records for the name servers
+/// named in the authority section.
 #[derive(Clone, Debug, Default)]
-pub struct Additional {}
+pub struct Additional(pub Vec<Answer>);
+
+/// An EDNS0 OPT pseudo-record distilled to the field we act on: the UDP
+/// payload size advertised in the CLASS field. (Extended rcode/version/flags
+/// live in the TTL field; we always send zeroes there.)
+#[derive(Clone, Copy, Debug)]
+pub struct Opt {
+    pub payload_size: u16,
+}
 
 #[derive(Clone, Debug, Default)]
 pub struct Message {
@@ -276,6 +492,8 @@ pub struct Message {
     pub answers: Vec<Answer>,
     pub authority: Authority,
     pub additional: Additional,
+    /// The OPT pseudo-record, when the peer speaks EDNS0.
+    pub opt: Option<Opt>,
 }
 
 impl TryInto<Vec<u8>> for Message {
@@ -286,15 +504,57 @@ impl TryInto<Vec<u8>> for Message {
         let header_bytes: [u8; 12] = self.header.try_into()?;
         r.extend_from_slice(&header_bytes);
 
-        self.questions.into_iter().for_each(|q| {
-            let q_bytes: Vec<u8> = q.into();
-            r.extend_from_slice(q_bytes.as_slice());
-        });
-
-        self.answers.into_iter().for_each(|a| {
-            let a_bytes: Vec<u8> = a.into();
-            r.extend_from_slice(a_bytes.as_slice());
-        });
+        // Name suffix -> offset within the output buffer, shared across every
+        // name in the message so later names can point back at earlier ones.
+        let mut offsets: HashMap<Vec<Label>, u16> = HashMap::new();
+
+        self.questions
+            .iter()
+            .for_each(|q| q.write(&mut r, &mut offsets));
+        self.answers
+            .iter()
+            .for_each(|a| a.write(&mut r, &mut offsets));
+        self.authority
+            .0
+            .iter()
+            .for_each(|a| a.write(&mut r, &mut offsets));
+        self.additional
+            .0
+            .iter()
+            .for_each(|a| a.write(&mut r, &mut offsets));
+
+        // The OPT pseudo-record is never name-compressed: root name, then the
+        // payload size in CLASS and zeroed extended rcode/version/flags.
+        if let Some(opt) = &self.opt {
+            r.put_u8(0);
+            r.put_u16(RecordType::Opt.into());
+            r.put_u16(opt.payload_size);
+            r.put_u32(0);
+            r.put_u16(0);
+        }
         Ok(r.into())
     }
 }
+
+#[test]
+fn test_txt_roundtrip_multiple_character_strings() {
+    // A payload longer than 255 bytes must split across several
+    // character-strings and reassemble to exactly the original bytes.
+    let payload: Vec<u8> = (0..600u16).map(|i| i as u8).collect();
+    let txt = TxtRData::from_payload(&payload);
+    assert_eq!(txt.0.len(), 3);
+    assert_eq!(txt.0[0].len(), 255);
+    assert_eq!(txt.0[2].len(), 90);
+
+    let decoded = TxtRData::from_bytes(&txt.to_bytes()).unwrap();
+    assert_eq!(decoded.payload(), payload);
+}
+
+#[test]
+fn test_txt_roundtrip_empty() {
+    let txt = TxtRData::from_payload(&[]);
+    assert!(txt.0.is_empty());
+
+    let decoded = TxtRData::from_bytes(&txt.to_bytes()).unwrap();
+    assert_eq!(decoded.payload(), Vec::<u8>::new());
+}